@@ -0,0 +1,27 @@
+//! Extension for the crate's main vhost-user backend connection to handle
+//! the `SET_GPU_SOCKET` request.
+//!
+//! The crate's real `Backend` type (the backend device's main vhost-user
+//! connection) is out of scope for this GPU display channel slice of the
+//! tree, so rather than defining a new, competing connection type here,
+//! `GpuSocketBackendExt` adds `handle_set_gpu_socket()` to any type that
+//! exposes its control socket via `AsRawFd` -- which the real `Backend`
+//! does, being built on the same `UnixStream`-backed main vhost-user
+//! connection as everywhere else in this crate.
+
+use std::os::unix::io::AsRawFd;
+
+use crate::vhost_user::gpu::GpuFrontend;
+use crate::vhost_user::gpu_socket::recv_gpu_socket_fd;
+use crate::vhost_user::Result;
+
+/// Adds `handle_set_gpu_socket()` to a main vhost-user backend connection.
+pub trait GpuSocketBackendExt: AsRawFd {
+    /// Handle an incoming `SET_GPU_SOCKET` request: read its header and
+    /// ancillary fd, and build the `GpuFrontend` endpoint from it.
+    fn handle_set_gpu_socket(&self) -> Result<GpuFrontend> {
+        recv_gpu_socket_fd(self)
+    }
+}
+
+impl<T: AsRawFd + ?Sized> GpuSocketBackendExt for T {}
@@ -0,0 +1,28 @@
+//! Extension for the crate's main vhost-user frontend connection to issue
+//! the `SET_GPU_SOCKET` request.
+//!
+//! The crate's real `Frontend` type (the VMM's main vhost-user connection)
+//! is out of scope for this GPU display channel slice of the tree, so
+//! rather than defining a new, competing connection type here,
+//! `GpuSocketFrontendExt` adds `set_gpu_socket()` to any type that exposes
+//! its control socket via `AsRawFd` -- which the real `Frontend` does,
+//! being built on the same `UnixStream`-backed main vhost-user connection
+//! as everywhere else in this crate.
+
+use std::os::unix::io::AsRawFd;
+
+use crate::vhost_user::gpu_socket::send_gpu_socket_fd;
+use crate::vhost_user::message::FrontendReq;
+use crate::vhost_user::Result;
+
+/// Adds `set_gpu_socket()` to a main vhost-user frontend connection.
+pub trait GpuSocketFrontendExt: AsRawFd {
+    /// Hand `gpu_sock` to the backend via `SET_GPU_SOCKET`, establishing
+    /// the dedicated GPU display channel described by
+    /// `crate::vhost_user::gpu`.
+    fn set_gpu_socket(&self, gpu_sock: &dyn AsRawFd) -> Result<()> {
+        send_gpu_socket_fd(self, gpu_sock, FrontendReq::GPU_SET_SOCKET)
+    }
+}
+
+impl<T: AsRawFd + ?Sized> GpuSocketFrontendExt for T {}
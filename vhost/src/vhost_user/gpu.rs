@@ -0,0 +1,440 @@
+//! Endpoints for the dedicated GPU display socket handed over by the main
+//! vhost-user connection via `VHOST_USER_GPU_SET_SOCKET`.
+//!
+//! `GpuBackend` is used by a vhost-user device backend to push display
+//! updates to the VMM, and `GpuFrontend` is the matching receiver used by
+//! the VMM to read them, mirroring the `Backend`/`Frontend` split of the
+//! main vhost-user protocol but speaking `VhostUserGpuMsgHeader` instead.
+//! See: <https://www.qemu.org/docs/master/interop/vhost-user-gpu.html>
+
+use std::cell::Cell;
+use std::io::ErrorKind;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use vm_memory::ByteValued;
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+
+use crate::vhost_user::gpu_edid::Edid;
+use crate::vhost_user::gpu_message::{
+    GpuBackendReq, VhostUserGpuCursorPos, VhostUserGpuCursorUpdate, VhostUserGpuDMABUFScanout,
+    VhostUserGpuDisplayInfo, VhostUserGpuEdidRequest, VhostUserGpuMsgHeader,
+    VhostUserGpuProtocolFeatures, VhostUserGpuScanout, VhostUserGpuUpdate,
+};
+use crate::vhost_user::message::VhostUserMsgValidator;
+use crate::vhost_user::{Error, Result};
+
+/// Maximum number of scanouts a `GET_DISPLAY_INFO` reply can describe.
+pub const VIRTIO_GPU_MAX_SCANOUTS: usize = 16;
+
+const HEADER_LEN: usize = mem::size_of::<VhostUserGpuMsgHeader<GpuBackendReq>>();
+
+/// Parse a `HEADER_LEN`-byte buffer read off the wire into a header.
+fn header_from_bytes(buf: &[u8; HEADER_LEN]) -> VhostUserGpuMsgHeader<GpuBackendReq> {
+    // SAFETY: `buf` is exactly HEADER_LEN bytes, matching the layout of
+    // VhostUserGpuMsgHeader<GpuBackendReq>.
+    unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const VhostUserGpuMsgHeader<GpuBackendReq>) }
+}
+
+/// Send all of `buf` on `sock`, looping over short writes: a `SOCK_STREAM`
+/// Unix socket is free to accept less than the whole buffer in one
+/// `sendmsg()` call, and since a partial send still desyncs the
+/// header/payload framing for every later message, it must be retried
+/// rather than treated as success. `fds` is only attached to the first
+/// `sendmsg()` call, matching how the kernel associates ancillary data
+/// with a single send.
+fn send_exact_with_fds(sock: &UnixStream, buf: &[u8], fds: &[RawFd]) -> Result<()> {
+    let mut sent = 0;
+    while sent < buf.len() {
+        let chunk_fds: &[RawFd] = if sent == 0 { fds } else { &[] };
+        let n = sock
+            .send_with_fds(&buf[sent..], chunk_fds)
+            .map_err(Error::SocketError)?;
+        if n == 0 {
+            return Err(Error::PartialMessage);
+        }
+        sent += n;
+    }
+    Ok(())
+}
+
+/// Fill all of `buf` from `sock`, looping over short reads so that a
+/// partial `recvmsg()` doesn't leave the rest of the in-flight message
+/// sitting in the socket buffer to be misread as the start of the next
+/// one. Up to `max_fds` ancillary file descriptors received alongside any
+/// chunk are collected and returned.
+fn recv_exact_with_fds(sock: &UnixStream, buf: &mut [u8], max_fds: usize) -> Result<Vec<RawFd>> {
+    let mut received = 0;
+    let mut fds = Vec::new();
+    while received < buf.len() {
+        let mut fd_buf = vec![0 as RawFd; max_fds];
+        let (n, fd_count) = sock
+            .recv_with_fds(&mut buf[received..], &mut fd_buf)
+            .map_err(|e| {
+                if e.kind() == ErrorKind::UnexpectedEof {
+                    Error::Disconnect
+                } else {
+                    Error::SocketError(e)
+                }
+            })?;
+        if n == 0 {
+            return Err(if received == 0 {
+                Error::Disconnect
+            } else {
+                Error::PartialMessage
+            });
+        }
+        received += n;
+        fds.extend_from_slice(&fd_buf[..fd_count]);
+    }
+    Ok(fds)
+}
+
+/// The endpoint used by a vhost-user device backend to drive the GPU
+/// display channel: it owns the `UnixStream` obtained from the main
+/// protocol's `VHOST_USER_GPU_SET_SOCKET` message and sends `GpuBackendReq`
+/// messages over it.
+pub struct GpuBackend {
+    sock: UnixStream,
+    acked_protocol_features: Cell<VhostUserGpuProtocolFeatures>,
+}
+
+impl GpuBackend {
+    /// Create a `GpuBackend` endpoint from an already connected socket.
+    pub fn from_stream(sock: UnixStream) -> Self {
+        GpuBackend {
+            sock,
+            acked_protocol_features: Cell::new(VhostUserGpuProtocolFeatures::empty()),
+        }
+    }
+
+    /// Returns whether `feature` was negotiated via `set_protocol_features()`.
+    fn check_feature(&self, feature: VhostUserGpuProtocolFeatures) -> Result<()> {
+        if self.acked_protocol_features.get().contains(feature) {
+            Ok(())
+        } else {
+            Err(Error::FeatureMismatch)
+        }
+    }
+
+    /// Get the protocol features supported by the peer.
+    pub fn get_protocol_features(&self) -> Result<VhostUserGpuProtocolFeatures> {
+        let bits: u64 = self.send_and_wait_reply(GpuBackendReq::GET_PROTOCOL_FEATURES, None)?;
+        Ok(VhostUserGpuProtocolFeatures::from_bits_truncate(bits))
+    }
+
+    /// Enable a subset of the protocol features returned by
+    /// `get_protocol_features()`. Gated requests (`get_edid()`,
+    /// `set_dmabuf_scanout2()`) are rejected locally until the
+    /// corresponding feature is acked here.
+    pub fn set_protocol_features(&self, features: VhostUserGpuProtocolFeatures) -> Result<()> {
+        self.send(
+            GpuBackendReq::SET_PROTOCOL_FEATURES,
+            Some(features.bits().as_slice()),
+            &[],
+        )?;
+        self.acked_protocol_features.set(features);
+        Ok(())
+    }
+
+    fn send(&self, code: GpuBackendReq, body: Option<&[u8]>, extra: &[u8]) -> Result<()> {
+        self.send_with_fds(code, body, extra, &[])
+    }
+
+    /// Send `body` after checking it with `VhostUserMsgValidator::is_valid()`,
+    /// rejecting obviously malformed requests before they reach the wire.
+    fn send_checked<T: ByteValued + VhostUserMsgValidator>(
+        &self,
+        code: GpuBackendReq,
+        body: &T,
+        extra: &[u8],
+    ) -> Result<()> {
+        if !body.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        self.send(code, Some(body.as_slice()), extra)
+    }
+
+    /// Same as `send_checked()`, but also attaches `fds` as ancillary data.
+    fn send_checked_with_fds<T: ByteValued + VhostUserMsgValidator>(
+        &self,
+        code: GpuBackendReq,
+        body: &T,
+        extra: &[u8],
+        fds: &[RawFd],
+    ) -> Result<()> {
+        if !body.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        self.send_with_fds(code, Some(body.as_slice()), extra, fds)
+    }
+
+    fn send_with_fds(
+        &self,
+        code: GpuBackendReq,
+        body: Option<&[u8]>,
+        extra: &[u8],
+        fds: &[RawFd],
+    ) -> Result<()> {
+        let body = body.unwrap_or(&[]);
+        let hdr = VhostUserGpuMsgHeader::new(code, 0, (body.len() + extra.len()) as u32);
+        let mut buf = Vec::with_capacity(HEADER_LEN + body.len() + extra.len());
+        buf.extend_from_slice(hdr.as_slice());
+        buf.extend_from_slice(body);
+        buf.extend_from_slice(extra);
+        send_exact_with_fds(&self.sock, &buf, fds)
+    }
+
+    fn send_and_wait_reply<T: ByteValued + Default + VhostUserMsgValidator>(
+        &self,
+        code: GpuBackendReq,
+        body: Option<&[u8]>,
+    ) -> Result<T> {
+        let req_hdr =
+            VhostUserGpuMsgHeader::new(code, 0, body.map(<[u8]>::len).unwrap_or(0) as u32);
+        self.send(code, body, &[])?;
+
+        let mut hdr_buf = [0u8; HEADER_LEN];
+        recv_exact_with_fds(&self.sock, &mut hdr_buf, 0)?;
+        let reply_hdr = header_from_bytes(&hdr_buf);
+        if !reply_hdr.is_reply_for(&req_hdr) {
+            return Err(Error::InvalidMessage);
+        }
+
+        let mut body_buf = vec![0u8; mem::size_of::<T>()];
+        if reply_hdr.get_size() as usize != body_buf.len() {
+            return Err(Error::PartialMessage);
+        }
+        if !body_buf.is_empty() {
+            recv_exact_with_fds(&self.sock, &mut body_buf, 0)?;
+        }
+        let mut reply = T::default();
+        reply.as_mut_slice().copy_from_slice(&body_buf);
+        if !reply.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        Ok(reply)
+    }
+
+    /// Get the preferred display configuration for every scanout.
+    pub fn get_display_info(&self) -> Result<[VhostUserGpuDisplayInfo; VIRTIO_GPU_MAX_SCANOUTS]> {
+        self.send_and_wait_reply(GpuBackendReq::GET_DISPLAY_INFO, None)
+    }
+
+    /// Set a scanout's resolution.
+    pub fn scanout(&self, scanout: &VhostUserGpuScanout) -> Result<()> {
+        self.send_checked(GpuBackendReq::SCANOUT, scanout, &[])
+    }
+
+    /// Flush `data` for the region described by `update` and request the
+    /// frontend to present it.
+    pub fn update(&self, update: &VhostUserGpuUpdate, data: &[u8]) -> Result<()> {
+        self.send_checked(GpuBackendReq::UPDATE, update, data)
+    }
+
+    /// Set/show the cursor position.
+    pub fn cursor_pos(&self, pos: &VhostUserGpuCursorPos) -> Result<()> {
+        self.send_checked(GpuBackendReq::CURSOR_POS, pos, &[])
+    }
+
+    /// Hide the cursor on the given scanout.
+    pub fn cursor_pos_hide(&self, scanout_id: u32) -> Result<()> {
+        let pos = VhostUserGpuCursorPos {
+            scanout_id,
+            x: 0,
+            y: 0,
+        };
+        self.send_checked(GpuBackendReq::CURSOR_POS_HIDE, &pos, &[])
+    }
+
+    /// Set/show the cursor bitmap at the position it carries.
+    pub fn cursor_update(&self, update: &VhostUserGpuCursorUpdate) -> Result<()> {
+        self.send_checked(GpuBackendReq::CURSOR_UPDATE, update, &[])
+    }
+
+    /// Set a scanout's resolution and share a DMABUF fd for its content,
+    /// passed as ancillary data. To disable the scanout, set its
+    /// width/height to 0 and pass `None`, so that no fd is sent.
+    pub fn set_dmabuf_scanout(
+        &self,
+        scanout: &VhostUserGpuDMABUFScanout,
+        fd: Option<&dyn AsRawFd>,
+    ) -> Result<()> {
+        let fds: Vec<RawFd> = fd.map(|fd| vec![fd.as_raw_fd()]).unwrap_or_default();
+        self.send_checked_with_fds(GpuBackendReq::DMABUF_SCANOUT, scanout, &[], &fds)
+    }
+
+    /// Same as `set_dmabuf_scanout()`, but also sends the DRM format
+    /// `modifier` appended to the message. Requires
+    /// `VHOST_USER_GPU_PROTOCOL_F_DMABUF2` to have been negotiated.
+    pub fn set_dmabuf_scanout2(
+        &self,
+        scanout: &VhostUserGpuDMABUFScanout,
+        fd: Option<&dyn AsRawFd>,
+        modifier: u64,
+    ) -> Result<()> {
+        self.check_feature(VhostUserGpuProtocolFeatures::VHOST_USER_GPU_PROTOCOL_F_DMABUF2)?;
+        let fds: Vec<RawFd> = fd.map(|fd| vec![fd.as_raw_fd()]).unwrap_or_default();
+        self.send_checked_with_fds(
+            GpuBackendReq::VHOST_USER_GPU_DMABUF_SCANOUT2,
+            scanout,
+            modifier.as_slice(),
+            &fds,
+        )
+    }
+
+    /// Flush the region described by `update`; no pixel payload is sent
+    /// since the buffer is already shared via a previously set DMABUF.
+    pub fn dmabuf_update(&self, update: &VhostUserGpuUpdate) -> Result<()> {
+        self.send_checked(GpuBackendReq::DMABUF_UPDATE, update, &[])
+    }
+
+    /// Retrieve the EDID blob the frontend has for `scanout_id`. Requires
+    /// `VHOST_USER_GPU_PROTOCOL_F_EDID` to have been negotiated.
+    pub fn get_edid(&self, scanout_id: u32) -> Result<Vec<u8>> {
+        self.check_feature(VhostUserGpuProtocolFeatures::VHOST_USER_GPU_PROTOCOL_F_EDID)?;
+        let req = VhostUserGpuEdidRequest { scanout_id };
+        if !req.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        let req_hdr =
+            VhostUserGpuMsgHeader::new(GpuBackendReq::GET_EDID, 0, req.as_slice().len() as u32);
+        self.send(GpuBackendReq::GET_EDID, Some(req.as_slice()), &[])?;
+
+        let mut hdr_buf = [0u8; HEADER_LEN];
+        recv_exact_with_fds(&self.sock, &mut hdr_buf, 0)?;
+        let reply_hdr = header_from_bytes(&hdr_buf);
+        if !reply_hdr.is_reply_for(&req_hdr) {
+            return Err(Error::InvalidMessage);
+        }
+        let mut edid = vec![0u8; reply_hdr.get_size() as usize];
+        if !edid.is_empty() {
+            recv_exact_with_fds(&self.sock, &mut edid, 0)?;
+        }
+        Ok(edid)
+    }
+}
+
+/// The endpoint used by the VMM/frontend side to receive `GpuBackendReq`
+/// messages sent by a `GpuBackend`.
+pub struct GpuFrontend {
+    sock: UnixStream,
+}
+
+impl GpuFrontend {
+    /// Create a `GpuFrontend` endpoint from an already connected socket.
+    pub fn from_stream(sock: UnixStream) -> Self {
+        GpuFrontend { sock }
+    }
+
+    /// Receive the next request header.
+    pub fn recv_header(&self) -> Result<VhostUserGpuMsgHeader<GpuBackendReq>> {
+        let mut hdr_buf = [0u8; HEADER_LEN];
+        recv_exact_with_fds(&self.sock, &mut hdr_buf, 0)?;
+        Ok(header_from_bytes(&hdr_buf))
+    }
+
+    /// Receive a fixed-size body matching `hdr`'s announced size.
+    pub fn recv_body<T: ByteValued + Default + VhostUserMsgValidator>(
+        &self,
+        hdr: &VhostUserGpuMsgHeader<GpuBackendReq>,
+    ) -> Result<T> {
+        if hdr.get_size() as usize != mem::size_of::<T>() {
+            return Err(Error::PartialMessage);
+        }
+        let mut buf = vec![0u8; mem::size_of::<T>()];
+        if !buf.is_empty() {
+            recv_exact_with_fds(&self.sock, &mut buf, 0)?;
+        }
+        let mut body = T::default();
+        body.as_mut_slice().copy_from_slice(&buf);
+        if !body.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        Ok(body)
+    }
+
+    /// Receive a fixed-size body matching `hdr`'s announced size, along
+    /// with any ancillary file descriptors sent with it (e.g. the DMABUF
+    /// fd carried by `DMABUF_SCANOUT`; see `recv_dmabuf_scanout2()` for
+    /// `VHOST_USER_GPU_DMABUF_SCANOUT2`).
+    pub fn recv_body_with_fds<T: ByteValued + Default + VhostUserMsgValidator>(
+        &self,
+        hdr: &VhostUserGpuMsgHeader<GpuBackendReq>,
+    ) -> Result<(T, Vec<RawFd>)> {
+        if hdr.get_size() as usize != mem::size_of::<T>() {
+            return Err(Error::PartialMessage);
+        }
+        let mut buf = vec![0u8; mem::size_of::<T>()];
+        if buf.is_empty() {
+            return Ok((T::default(), Vec::new()));
+        }
+        let fds = recv_exact_with_fds(&self.sock, &mut buf, 1)?;
+        let mut body = T::default();
+        body.as_mut_slice().copy_from_slice(&buf);
+        if !body.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        Ok((body, fds))
+    }
+
+    /// Receive a `VHOST_USER_GPU_DMABUF_SCANOUT2` body: a
+    /// `VhostUserGpuDMABUFScanout` followed by the DRM format modifier,
+    /// which `recv_body_with_fds()` can't handle since its announced size
+    /// doesn't match `size_of::<VhostUserGpuDMABUFScanout>()`, along with
+    /// the DMABUF fd sent as ancillary data.
+    pub fn recv_dmabuf_scanout2(
+        &self,
+        hdr: &VhostUserGpuMsgHeader<GpuBackendReq>,
+    ) -> Result<(VhostUserGpuDMABUFScanout, u64, Vec<RawFd>)> {
+        const SCANOUT_LEN: usize = mem::size_of::<VhostUserGpuDMABUFScanout>();
+        const MODIFIER_LEN: usize = mem::size_of::<u64>();
+
+        if hdr.get_size() as usize != SCANOUT_LEN + MODIFIER_LEN {
+            return Err(Error::PartialMessage);
+        }
+        let mut buf = vec![0u8; SCANOUT_LEN + MODIFIER_LEN];
+        let fds = recv_exact_with_fds(&self.sock, &mut buf, 1)?;
+
+        let mut scanout = VhostUserGpuDMABUFScanout::default();
+        scanout.as_mut_slice().copy_from_slice(&buf[..SCANOUT_LEN]);
+        if !scanout.is_valid() {
+            return Err(Error::InvalidMessage);
+        }
+        let modifier = u64::from_ne_bytes(buf[SCANOUT_LEN..].try_into().unwrap());
+
+        Ok((scanout, modifier, fds))
+    }
+
+    /// Reply to a `GET_EDID` request with a generated EDID blob (see
+    /// `crate::vhost_user::gpu_edid`).
+    pub fn send_edid_reply(
+        &self,
+        req_hdr: &VhostUserGpuMsgHeader<GpuBackendReq>,
+        edid: &Edid,
+    ) -> Result<()> {
+        let bytes = edid.as_bytes();
+        let mut reply_hdr = VhostUserGpuMsgHeader::new(req_hdr.get_code()?, 0, bytes.len() as u32);
+        reply_hdr.set_reply(true);
+        let mut buf = Vec::with_capacity(HEADER_LEN + bytes.len());
+        buf.extend_from_slice(reply_hdr.as_slice());
+        buf.extend_from_slice(bytes);
+        send_exact_with_fds(&self.sock, &buf, &[])
+    }
+
+    /// Reply to the request described by `req_hdr` with `body`.
+    pub fn send_reply<T: ByteValued>(
+        &self,
+        req_hdr: &VhostUserGpuMsgHeader<GpuBackendReq>,
+        body: &T,
+    ) -> Result<()> {
+        let mut reply_hdr =
+            VhostUserGpuMsgHeader::new(req_hdr.get_code()?, 0, mem::size_of::<T>() as u32);
+        reply_hdr.set_reply(true);
+        let mut buf = Vec::with_capacity(HEADER_LEN + mem::size_of::<T>());
+        buf.extend_from_slice(reply_hdr.as_slice());
+        buf.extend_from_slice(body.as_slice());
+        send_exact_with_fds(&self.sock, &buf, &[])
+    }
+}
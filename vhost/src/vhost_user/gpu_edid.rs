@@ -0,0 +1,375 @@
+//! Generation of EDID blobs for `GET_EDID` replies on the GPU channel.
+//!
+//! Only the parts of the EDID 1.4 base block and the CTA-861 extension
+//! block needed to describe a single preferred mode are produced; this is
+//! enough for a GPU backend to report a synthetic display to the guest.
+//! See: <https://en.wikipedia.org/wiki/Extended_Display_Identification_Data>
+
+use crate::vhost_user::{Error, Result};
+
+/// Size of an EDID base or extension block.
+pub const EDID_BLOCK_SIZE: usize = 128;
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+/// Largest active dimension a single detailed timing descriptor can
+/// express (12 bits).
+const MAX_DETAILED_TIMING_DIMENSION: u32 = 0xFFF;
+
+/// Largest pixel clock, in 10kHz units, a detailed timing descriptor can
+/// express (16 bits).
+const MAX_DETAILED_TIMING_PIXEL_CLOCK_10KHZ: u32 = 0xFFFF;
+
+/// Largest horizontal sync offset/width a detailed timing descriptor can
+/// express (10 bits).
+const MAX_DETAILED_TIMING_H_SYNC: u32 = 0x3FF;
+
+/// Largest vertical sync offset/width a detailed timing descriptor can
+/// express (6 bits).
+const MAX_DETAILED_TIMING_V_SYNC: u32 = 0x3F;
+
+/// Preferred mode and optional physical display characteristics to embed
+/// in a generated EDID.
+#[derive(Copy, Clone, Debug)]
+pub struct EdidInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in Hz. Defaults to 60 if `None`.
+    pub refresh_rate_hz: Option<u32>,
+    /// Physical size of the display, in millimeters.
+    pub phys_size_mm: Option<(u32, u32)>,
+}
+
+impl EdidInfo {
+    /// Build an `EdidInfo` for `width`x`height` with all other fields
+    /// defaulted.
+    pub fn new(width: u32, height: u32) -> Self {
+        EdidInfo {
+            width,
+            height,
+            refresh_rate_hz: None,
+            phys_size_mm: None,
+        }
+    }
+}
+
+/// An EDID blob: a 128-byte base block, plus an optional 128-byte
+/// CTA-861 extension block when the requested mode does not fit in the
+/// base block's detailed timing descriptor.
+pub struct Edid {
+    data: [u8; EDID_BLOCK_SIZE * 2],
+    len: usize,
+}
+
+impl Edid {
+    /// The serialized EDID bytes, ready to be copied into a `GET_EDID`
+    /// reply payload.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// Generate an EDID blob describing `info` as the preferred (and only)
+/// mode.
+///
+/// A single detailed timing descriptor (base block or CTA-861 extension
+/// block) can only express active dimensions up to 4095 and a pixel clock
+/// up to 655.35MHz; `info` describing a mode outside that range is
+/// rejected rather than silently truncated.
+pub fn generate(info: &EdidInfo) -> Result<Edid> {
+    let refresh_hz = info.refresh_rate_hz.unwrap_or(60);
+    let needs_extension =
+        info.width > MAX_DETAILED_TIMING_DIMENSION || info.height > MAX_DETAILED_TIMING_DIMENSION;
+
+    let mut data = [0u8; EDID_BLOCK_SIZE * 2];
+    write_base_block(
+        &mut data[..EDID_BLOCK_SIZE],
+        info,
+        refresh_hz,
+        needs_extension,
+    )?;
+
+    let len = if needs_extension {
+        write_extension_block(&mut data[EDID_BLOCK_SIZE..], info, refresh_hz)?;
+        EDID_BLOCK_SIZE * 2
+    } else {
+        EDID_BLOCK_SIZE
+    };
+
+    Ok(Edid { data, len })
+}
+
+fn write_base_block(
+    block: &mut [u8],
+    info: &EdidInfo,
+    refresh_hz: u32,
+    has_extension: bool,
+) -> Result<()> {
+    block[0..8].copy_from_slice(&EDID_HEADER);
+
+    // Manufacturer ID "VHU" (vhost-user), packed 5 bits per letter.
+    let mfg_id = pack_manufacturer_id(b'V', b'H', b'U');
+    block[8..10].copy_from_slice(&mfg_id.to_be_bytes());
+    // Product code, serial number: none assigned.
+    block[10..12].copy_from_slice(&0u16.to_le_bytes());
+    block[12..16].copy_from_slice(&0u32.to_le_bytes());
+    // Week of manufacture (unspecified) / year of manufacture (unknown).
+    block[16] = 0;
+    block[17] = 0;
+
+    block[18] = 1; // EDID version
+    block[19] = 4; // EDID revision
+
+    // Video input definition: digital input, other parameters unspecified.
+    block[20] = 0x80;
+    let (h_size_cm, v_size_cm) = info
+        .phys_size_mm
+        .map(|(w, h)| ((w / 10) as u8, (h / 10) as u8))
+        .unwrap_or((0, 0));
+    block[21] = h_size_cm;
+    block[22] = v_size_cm;
+    // Gamma: (gamma * 100) - 100, for a gamma of 2.2.
+    block[23] = 120;
+    // Feature support: bit1 set, the first detailed timing is preferred.
+    block[24] = 0x02;
+
+    write_chromaticity(&mut block[25..35]);
+
+    // Established and standard timings: none declared.
+    block[35..38].fill(0);
+    for std_timing in block[38..54].chunks_exact_mut(2) {
+        std_timing.copy_from_slice(&[0x01, 0x01]);
+    }
+
+    // When the mode needs an extension block, the base block's own
+    // detailed timing can't describe it (same 12-bit dimension limit as
+    // the extension's), so it instead declares the largest representable
+    // approximation; the extension block below carries the real mode.
+    let (base_width, base_height) = if has_extension {
+        (
+            info.width.min(MAX_DETAILED_TIMING_DIMENSION),
+            info.height.min(MAX_DETAILED_TIMING_DIMENSION),
+        )
+    } else {
+        (info.width, info.height)
+    };
+    write_detailed_timing(
+        &mut block[54..72],
+        base_width,
+        base_height,
+        refresh_hz,
+        info.phys_size_mm,
+    )?;
+    write_monitor_descriptor(&mut block[72..90], b"vhost-gpu");
+    write_dummy_descriptor(&mut block[90..108]);
+    write_dummy_descriptor(&mut block[108..126]);
+
+    block[126] = u8::from(has_extension);
+    block[127] = checksum(&block[..127]);
+    Ok(())
+}
+
+fn write_extension_block(block: &mut [u8], info: &EdidInfo, refresh_hz: u32) -> Result<()> {
+    block[0] = 0x02; // CTA-861 extension tag
+    block[1] = 3; // revision
+                  // Offset to the first detailed timing descriptor.
+    block[2] = 4;
+    block[3] = 0x00; // no underscan/audio/YCbCr, no native DTDs declared
+    block[4..].fill(0);
+
+    write_detailed_timing(
+        &mut block[4..22],
+        info.width,
+        info.height,
+        refresh_hz,
+        info.phys_size_mm,
+    )?;
+
+    block[127] = checksum(&block[..127]);
+    Ok(())
+}
+
+fn pack_manufacturer_id(a: u8, b: u8, c: u8) -> u16 {
+    let letter = |ch: u8| u16::from(ch - b'A' + 1);
+    (letter(a) << 10) | (letter(b) << 5) | letter(c)
+}
+
+/// Pack the sRGB chromaticity coordinates, the common default for a
+/// synthetic display with no vendor-specific calibration data.
+fn write_chromaticity(buf: &mut [u8]) {
+    // (x, y) pairs in the EDID's 10-bit fixed point format (value / 1024).
+    const RED: (u16, u16) = (655, 338);
+    const GREEN: (u16, u16) = (307, 614);
+    const BLUE: (u16, u16) = (154, 61);
+    const WHITE: (u16, u16) = (320, 337);
+
+    buf[0] =
+        (((RED.0 & 0x3) << 6) | ((RED.1 & 0x3) << 4) | ((GREEN.0 & 0x3) << 2) | (GREEN.1 & 0x3))
+            as u8;
+    buf[1] =
+        (((BLUE.0 & 0x3) << 6) | ((BLUE.1 & 0x3) << 4) | ((WHITE.0 & 0x3) << 2) | (WHITE.1 & 0x3))
+            as u8;
+    buf[2] = (RED.0 >> 2) as u8;
+    buf[3] = (RED.1 >> 2) as u8;
+    buf[4] = (GREEN.0 >> 2) as u8;
+    buf[5] = (GREEN.1 >> 2) as u8;
+    buf[6] = (BLUE.0 >> 2) as u8;
+    buf[7] = (BLUE.1 >> 2) as u8;
+    buf[8] = (WHITE.0 >> 2) as u8;
+    buf[9] = (WHITE.1 >> 2) as u8;
+}
+
+/// Pack an 18-byte detailed timing descriptor for `width`x`height` at
+/// `refresh_hz`, with blanking/sync figures derived as simple fractions
+/// of the active area (there is no real CRT to time against).
+///
+/// Returns `Error::InvalidMessage` if `width`/`height`, the resulting pixel
+/// clock, or any of the derived sync offsets/widths don't fit the
+/// descriptor's fields, rather than silently clamping or truncating them.
+fn write_detailed_timing(
+    buf: &mut [u8],
+    width: u32,
+    height: u32,
+    refresh_hz: u32,
+    phys_size_mm: Option<(u32, u32)>,
+) -> Result<()> {
+    if width > MAX_DETAILED_TIMING_DIMENSION || height > MAX_DETAILED_TIMING_DIMENSION {
+        return Err(Error::InvalidMessage);
+    }
+    let h_active = width;
+    let v_active = height;
+    let h_blank = (h_active / 4).max(8);
+    let v_blank = (v_active / 20).max(4);
+    let h_sync_offset = (h_blank / 3).max(1);
+    let h_sync_width = (h_blank / 4).max(1);
+    let v_sync_offset = (v_blank / 3).max(1);
+    let v_sync_width = (v_blank / 10).max(1);
+    if h_sync_offset > MAX_DETAILED_TIMING_H_SYNC || h_sync_width > MAX_DETAILED_TIMING_H_SYNC {
+        return Err(Error::InvalidMessage);
+    }
+    if v_sync_offset > MAX_DETAILED_TIMING_V_SYNC || v_sync_width > MAX_DETAILED_TIMING_V_SYNC {
+        return Err(Error::InvalidMessage);
+    }
+
+    let pixel_clock_10khz = (h_active + h_blank) * (v_active + v_blank) * refresh_hz / 10_000;
+    if pixel_clock_10khz > MAX_DETAILED_TIMING_PIXEL_CLOCK_10KHZ {
+        return Err(Error::InvalidMessage);
+    }
+    buf[0..2].copy_from_slice(&(pixel_clock_10khz as u16).to_le_bytes());
+
+    buf[2] = h_active as u8;
+    buf[3] = h_blank as u8;
+    buf[4] = (((h_active >> 8) & 0xF) << 4) as u8 | ((h_blank >> 8) & 0xF) as u8;
+    buf[5] = v_active as u8;
+    buf[6] = v_blank as u8;
+    buf[7] = (((v_active >> 8) & 0xF) << 4) as u8 | ((v_blank >> 8) & 0xF) as u8;
+    buf[8] = h_sync_offset as u8;
+    buf[9] = h_sync_width as u8;
+    buf[10] = (((v_sync_offset & 0xF) << 4) | (v_sync_width & 0xF)) as u8;
+    buf[11] = ((((h_sync_offset >> 8) & 0x3) << 6)
+        | (((h_sync_width >> 8) & 0x3) << 4)
+        | (((v_sync_offset >> 4) & 0x3) << 2)
+        | ((v_sync_width >> 4) & 0x3)) as u8;
+
+    let (h_size_mm, v_size_mm) = phys_size_mm.unwrap_or((0, 0));
+    buf[12] = h_size_mm as u8;
+    buf[13] = v_size_mm as u8;
+    buf[14] = (((h_size_mm >> 8) & 0xF) << 4) as u8 | ((v_size_mm >> 8) & 0xF) as u8;
+    buf[15] = 0; // h border
+    buf[16] = 0; // v border
+                 // Digital separate sync, both polarities positive, non-interlaced.
+    buf[17] = 0x1E;
+    Ok(())
+}
+
+/// Pack an 18-byte "display name" monitor descriptor. Per spec, the text
+/// field is terminated with 0x0A and padded with spaces.
+fn write_monitor_descriptor(buf: &mut [u8], name: &[u8]) {
+    buf[0..3].fill(0);
+    buf[3] = 0xFC; // display product name tag
+    buf[4] = 0;
+    let text = &mut buf[5..18];
+    let n = name.len().min(text.len());
+    text[..n].copy_from_slice(&name[..n]);
+    if n < text.len() {
+        text[n] = 0x0A;
+        for b in text[n + 1..].iter_mut() {
+            *b = 0x20;
+        }
+    }
+}
+
+/// Pack an 18-byte unused ("dummy") descriptor.
+fn write_dummy_descriptor(buf: &mut [u8]) {
+    buf[0..3].fill(0);
+    buf[3] = 0x10; // dummy descriptor tag
+    buf[4..18].fill(0);
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    let sum: u32 = bytes.iter().map(|&b| b as u32).sum();
+    ((256 - (sum % 256)) % 256) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_blocks_sum_to_zero() {
+        let edid = generate(&EdidInfo::new(1920, 1080)).unwrap();
+        let bytes = edid.as_bytes();
+        assert_eq!(bytes.len(), EDID_BLOCK_SIZE);
+        let sum: u32 = bytes[..EDID_BLOCK_SIZE].iter().map(|&b| b as u32).sum();
+        assert_eq!(sum % 256, 0);
+    }
+
+    #[test]
+    fn generate_with_extension_block_sums_to_zero() {
+        let edid = generate(&EdidInfo::new(7680, 4320)).unwrap();
+        let bytes = edid.as_bytes();
+        assert_eq!(bytes.len(), EDID_BLOCK_SIZE * 2);
+        for block in bytes.chunks_exact(EDID_BLOCK_SIZE) {
+            let sum: u32 = block.iter().map(|&b| b as u32).sum();
+            assert_eq!(sum % 256, 0);
+        }
+    }
+
+    #[test]
+    fn detailed_timing_rejects_oversized_dimensions() {
+        let mut buf = [0u8; 18];
+        assert!(
+            write_detailed_timing(&mut buf, MAX_DETAILED_TIMING_DIMENSION + 1, 480, 60, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn detailed_timing_rejects_overflowing_sync_fields() {
+        // width=100, height=4095 derives a v_sync_offset of 68, which does
+        // not fit the descriptor's 6-bit field (max 63) and must not be
+        // silently truncated.
+        let mut buf = [0u8; 18];
+        assert!(write_detailed_timing(&mut buf, 100, 4095, 60, None).is_err());
+    }
+
+    #[test]
+    fn detailed_timing_packs_active_dimensions() {
+        let mut buf = [0u8; 18];
+        write_detailed_timing(&mut buf, 1920, 1080, 60, None).unwrap();
+        assert_eq!(buf[2], 1920u32 as u8);
+        assert_eq!(buf[5], 1080u32 as u8);
+        assert_eq!((buf[4] >> 4) & 0xF, ((1920u32 >> 8) & 0xF) as u8);
+        assert_eq!((buf[7] >> 4) & 0xF, ((1080u32 >> 8) & 0xF) as u8);
+    }
+
+    #[test]
+    fn checksum_makes_block_sum_to_zero() {
+        let mut block = [0u8; EDID_BLOCK_SIZE];
+        block[0] = 1;
+        block[10] = 42;
+        block[127] = checksum(&block[..127]);
+        let sum: u32 = block.iter().map(|&b| b as u32).sum();
+        assert_eq!(sum % 256, 0);
+    }
+}
@@ -8,8 +8,12 @@ use crate::vhost_user::message::{
 use crate::vhost_user::Error;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::mem::size_of;
 use vm_memory::ByteValued;
 
+/// Maximum cursor side length, in pixels, per the vhost-user-gpu protocol.
+pub const VHOST_USER_GPU_CURSOR_SIDE: usize = 64;
+
 enum_value! {
     /// Type of requests sending from gpu backends to gpu frontends.
     #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -25,6 +29,8 @@ enum_value! {
         CURSOR_POS,
         /// Set/hide the cursor.
         CURSOR_POS_HIDE,
+        /// Set/show the cursor bitmap, hotspot and position.
+        CURSOR_UPDATE,
         /// Set the scanout resolution.
         /// To disable a scanout, the dimensions width/height are set to 0.
         SCANOUT,
@@ -174,4 +180,227 @@ impl<T: Req> VhostUserMsgValidator for VhostUserGpuMsgHeader<T> {
 
 impl<R: Req> MsgHeader for VhostUserGpuMsgHeader<R> {
     type Request = R;
-}
\ No newline at end of file
+}
+
+/// One scanout entry of a `GET_DISPLAY_INFO` reply.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VhostUserGpuDisplayInfo {
+    /// Preferred position and size of the scanout.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Non-zero if the scanout is enabled.
+    pub enabled: u32,
+    pub flags: u32,
+}
+// SAFETY: All fields of VhostUserGpuDisplayInfo are POD.
+unsafe impl ByteValued for VhostUserGpuDisplayInfo {}
+
+impl VhostUserMsgValidator for VhostUserGpuDisplayInfo {
+    fn is_valid(&self) -> bool {
+        (self.width as u64) * (self.height as u64) <= MAX_MSG_SIZE as u64
+    }
+}
+
+/// Cursor position on a scanout. Also used to hide the cursor, in which
+/// case `x`/`y` are ignored.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VhostUserGpuCursorPos {
+    pub scanout_id: u32,
+    pub x: u32,
+    pub y: u32,
+}
+// SAFETY: All fields of VhostUserGpuCursorPos are POD.
+unsafe impl ByteValued for VhostUserGpuCursorPos {}
+
+impl VhostUserMsgValidator for VhostUserGpuCursorPos {
+    fn is_valid(&self) -> bool {
+        true
+    }
+}
+
+/// Cursor bitmap update: a 64x64 RGBA cursor image and its hotspot, shown
+/// at the position carried in `pos`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct VhostUserGpuCursorUpdate {
+    pub pos: VhostUserGpuCursorPos,
+    pub hot_x: u32,
+    pub hot_y: u32,
+    pub data: [u32; VHOST_USER_GPU_CURSOR_SIDE * VHOST_USER_GPU_CURSOR_SIDE],
+}
+
+impl Default for VhostUserGpuCursorUpdate {
+    fn default() -> Self {
+        VhostUserGpuCursorUpdate {
+            pos: VhostUserGpuCursorPos::default(),
+            hot_x: 0,
+            hot_y: 0,
+            data: [0; VHOST_USER_GPU_CURSOR_SIDE * VHOST_USER_GPU_CURSOR_SIDE],
+        }
+    }
+}
+
+// SAFETY: All fields of VhostUserGpuCursorUpdate are POD.
+unsafe impl ByteValued for VhostUserGpuCursorUpdate {}
+
+impl VhostUserMsgValidator for VhostUserGpuCursorUpdate {
+    fn is_valid(&self) -> bool {
+        self.hot_x as usize <= VHOST_USER_GPU_CURSOR_SIDE
+            && self.hot_y as usize <= VHOST_USER_GPU_CURSOR_SIDE
+    }
+}
+
+/// Request to set a scanout's resolution. Width/height of 0 disables it.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VhostUserGpuScanout {
+    pub scanout_id: u32,
+    pub width: u32,
+    pub height: u32,
+}
+// SAFETY: All fields of VhostUserGpuScanout are POD.
+unsafe impl ByteValued for VhostUserGpuScanout {}
+
+impl VhostUserMsgValidator for VhostUserGpuScanout {
+    fn is_valid(&self) -> bool {
+        (self.width as u64) * (self.height as u64) <= MAX_MSG_SIZE as u64
+    }
+}
+
+/// Request to flush an updated region of a scanout. The pixel data for the
+/// region follows this header as a variable-length payload.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VhostUserGpuUpdate {
+    pub scanout_id: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+// SAFETY: All fields of VhostUserGpuUpdate are POD.
+unsafe impl ByteValued for VhostUserGpuUpdate {}
+
+impl VhostUserMsgValidator for VhostUserGpuUpdate {
+    fn is_valid(&self) -> bool {
+        (self.width as u64) * (self.height as u64) * (size_of::<u32>() as u64)
+            <= MAX_MSG_SIZE as u64
+    }
+}
+
+/// Request to set a scanout's resolution and share a DMABUF file
+/// descriptor (passed out of band as ancillary data) for its content.
+/// Width/height of 0 disables the scanout, with no fd passed.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VhostUserGpuDMABUFScanout {
+    pub scanout_id: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub fd_width: u32,
+    pub fd_height: u32,
+    pub fd_stride: u32,
+    pub fd_flags: u32,
+    pub fd_drm_fourcc: u32,
+}
+// SAFETY: All fields of VhostUserGpuDMABUFScanout are POD.
+unsafe impl ByteValued for VhostUserGpuDMABUFScanout {}
+
+impl VhostUserMsgValidator for VhostUserGpuDMABUFScanout {
+    fn is_valid(&self) -> bool {
+        (self.fd_width as u64) * (self.fd_height as u64) <= MAX_MSG_SIZE as u64
+    }
+}
+
+/// Request for the EDID blob of a given scanout. Requires
+/// `VHOST_USER_GPU_PROTOCOL_F_EDID` to have been negotiated.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VhostUserGpuEdidRequest {
+    pub scanout_id: u32,
+}
+// SAFETY: All fields of VhostUserGpuEdidRequest are POD.
+unsafe impl ByteValued for VhostUserGpuEdidRequest {}
+
+impl VhostUserMsgValidator for VhostUserGpuEdidRequest {
+    fn is_valid(&self) -> bool {
+        true
+    }
+}
+
+bitflags! {
+    /// Features that can be negotiated on the GPU channel via
+    /// `GET_PROTOCOL_FEATURES`/`SET_PROTOCOL_FEATURES`.
+    pub struct VhostUserGpuProtocolFeatures: u64 {
+        /// The peer supports `GET_EDID`.
+        const VHOST_USER_GPU_PROTOCOL_F_EDID = 1 << 0;
+        /// The peer supports `VHOST_USER_GPU_DMABUF_SCANOUT2`.
+        const VHOST_USER_GPU_PROTOCOL_F_DMABUF2 = 1 << 1;
+    }
+}
+
+impl Default for VhostUserGpuProtocolFeatures {
+    fn default() -> Self {
+        VhostUserGpuProtocolFeatures::empty()
+    }
+}
+
+// The raw bitmask carried by GET_PROTOCOL_FEATURES replies has no
+// unused-bit constraint of its own; `VhostUserGpuProtocolFeatures` is what
+// actually validates it, via `from_bits_truncate()`.
+impl VhostUserMsgValidator for u64 {
+    fn is_valid(&self) -> bool {
+        true
+    }
+}
+
+// GET_DISPLAY_INFO replies with one entry per scanout; valid iff every
+// entry is.
+impl<T: VhostUserMsgValidator, const N: usize> VhostUserMsgValidator for [T; N] {
+    fn is_valid(&self) -> bool {
+        self.iter().all(VhostUserMsgValidator::is_valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_info_is_valid() {
+        let mut info = VhostUserGpuDisplayInfo::default();
+        info.width = 1920;
+        info.height = 1080;
+        assert!(info.is_valid());
+
+        // Dimensions whose product overflows MAX_MSG_SIZE must be rejected.
+        info.width = u32::MAX;
+        info.height = u32::MAX;
+        assert!(!info.is_valid());
+    }
+
+    #[test]
+    fn u64_is_always_valid() {
+        assert!(0u64.is_valid());
+        assert!(u64::MAX.is_valid());
+    }
+
+    #[test]
+    fn array_is_valid_iff_every_entry_is() {
+        let mut ok = VhostUserGpuDisplayInfo::default();
+        ok.width = 1;
+        ok.height = 1;
+        let mut bad = VhostUserGpuDisplayInfo::default();
+        bad.width = u32::MAX;
+        bad.height = u32::MAX;
+
+        assert!([ok, ok].is_valid());
+        assert!(![ok, bad].is_valid());
+    }
+}
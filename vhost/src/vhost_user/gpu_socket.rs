@@ -0,0 +1,102 @@
+//! Wiring for `VHOST_USER_GPU_SET_SOCKET`, the main vhost-user request that
+//! hands the GPU display socket fd from the frontend to the backend. This
+//! uses `FrontendReq::GPU_SET_SOCKET` from the crate's shared
+//! `vhost_user::message` types, the same main-protocol request enum
+//! `BackendReq` is drawn from in `vhost_user::gpu_message`, rather than a
+//! request code private to this module.
+//!
+//! `GpuSocketFrontendExt::set_gpu_socket()` and
+//! `GpuSocketBackendExt::handle_set_gpu_socket()` (`vhost_user::frontend`,
+//! `vhost_user::backend`) call into the helpers here to do the actual
+//! header and ancillary-fd handling, and to build the resulting
+//! `GpuFrontend` endpoint.
+
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+
+use crate::vhost_user::gpu::GpuFrontend;
+use crate::vhost_user::message::FrontendReq;
+use crate::vhost_user::{Error, Result};
+
+/// Length of a main vhost-user message header: request, flags, size, each
+/// a native-endian `u32`.
+const MAIN_HEADER_LEN: usize = 12;
+
+fn main_header_bytes(request: FrontendReq, size: u32) -> [u8; MAIN_HEADER_LEN] {
+    let mut buf = [0u8; MAIN_HEADER_LEN];
+    buf[0..4].copy_from_slice(&u32::from(request).to_ne_bytes());
+    buf[4..8].copy_from_slice(&0u32.to_ne_bytes()); // flags
+    buf[8..12].copy_from_slice(&size.to_ne_bytes());
+    buf
+}
+
+/// Send `request` on `ctrl_sock`, the main vhost-user control socket, with
+/// `gpu_sock` attached as ancillary data. Used by
+/// `GpuSocketFrontendExt::set_gpu_socket()`.
+pub(crate) fn send_gpu_socket_fd(
+    ctrl_sock: &dyn AsRawFd,
+    gpu_sock: &dyn AsRawFd,
+    request: FrontendReq,
+) -> Result<()> {
+    // SAFETY: we only read ctrl_sock's raw fd to send through it; ownership
+    // stays with the caller.
+    let ctrl_sock = unsafe { UnixStreamRef::new(ctrl_sock.as_raw_fd()) };
+    let hdr = main_header_bytes(request, 0);
+    ctrl_sock
+        .send_with_fds(&hdr, &[gpu_sock.as_raw_fd()])
+        .map_err(Error::SocketError)?;
+    Ok(())
+}
+
+/// Read a `GPU_SET_SOCKET` request and its ancillary fd off `ctrl_sock`,
+/// and build the `GpuFrontend` endpoint from it. Used by
+/// `GpuSocketBackendExt::handle_set_gpu_socket()`.
+pub(crate) fn recv_gpu_socket_fd(ctrl_sock: &dyn AsRawFd) -> Result<GpuFrontend> {
+    // SAFETY: we only read ctrl_sock's raw fd to receive from it; ownership
+    // stays with the caller.
+    let ctrl_sock = unsafe { UnixStreamRef::new(ctrl_sock.as_raw_fd()) };
+    let mut hdr_buf = [0u8; MAIN_HEADER_LEN];
+    let mut fd_buf = [0 as RawFd; 1];
+    let (n, fd_count) = ctrl_sock
+        .recv_with_fds(&mut hdr_buf, &mut fd_buf)
+        .map_err(Error::SocketError)?;
+    if n != MAIN_HEADER_LEN {
+        return Err(Error::PartialMessage);
+    }
+    let request = u32::from_ne_bytes(hdr_buf[0..4].try_into().unwrap());
+    match FrontendReq::try_from(request) {
+        Ok(FrontendReq::GPU_SET_SOCKET) => {}
+        _ => return Err(Error::InvalidMessage),
+    }
+    if fd_count != 1 {
+        return Err(Error::IncorrectFds);
+    }
+
+    // SAFETY: `fd_buf[0]` was just received via SCM_RIGHTS from the main
+    // control socket and ownership is transferred to us.
+    let gpu_sock = unsafe { UnixStream::from_raw_fd(fd_buf[0]) };
+    Ok(GpuFrontend::from_stream(gpu_sock))
+}
+
+/// A non-owning `UnixStream` view over a borrowed fd, used so we can reuse
+/// `ScmSocket` without taking ownership of the main control socket's fd.
+struct UnixStreamRef(std::mem::ManuallyDrop<UnixStream>);
+
+impl UnixStreamRef {
+    /// # Safety
+    /// `fd` must name a valid, open socket for the lifetime of the
+    /// returned value, and the caller must not let it outlive `fd`'s owner.
+    unsafe fn new(fd: RawFd) -> Self {
+        UnixStreamRef(std::mem::ManuallyDrop::new(UnixStream::from_raw_fd(fd)))
+    }
+}
+
+impl std::ops::Deref for UnixStreamRef {
+    type Target = UnixStream;
+
+    fn deref(&self) -> &UnixStream {
+        &self.0
+    }
+}